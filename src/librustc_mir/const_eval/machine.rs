@@ -4,6 +4,7 @@ use rustc_middle::ty::{self, Ty};
 use std::borrow::{Borrow, Cow};
 use std::collections::hash_map::Entry;
 use std::hash::Hash;
+use std::time::Instant;
 
 use rustc_data_structures::fx::FxHashMap;
 
@@ -11,17 +12,125 @@ use rustc_ast::ast::Mutability;
 use rustc_hir::def_id::DefId;
 use rustc_middle::mir::AssertMessage;
 use rustc_span::symbol::Symbol;
+use rustc_target::abi::Size;
 
 use crate::interpret::{
-    self, AllocId, Allocation, Frame, GlobalId, ImmTy, InterpCx, InterpResult, Memory, MemoryKind,
-    OpTy, PlaceTy, Pointer, Scalar,
+    self, AllocId, Allocation, Frame, GlobalId, ImmTy, Immediate, InterpCx, InterpResult, Memory,
+    MemoryKind, OpTy, PlaceTy, Pointer, Scalar, StackPopCleanup,
 };
 
 use super::error::*;
 
-impl<'mir, 'tcx> InterpCx<'mir, 'tcx, CompileTimeInterpreter> {
-    /// Evaluate a const function where all arguments (if any) are zero-sized types.
-    /// The evaluation is memoized thanks to the query system.
+/// A cache key for memoizing `const fn` calls whose arguments are not all ZSTs (the
+/// all-ZST case is already memoized through the query system above). Two calls with
+/// `eq` keys are guaranteed to produce the same result, so the second one can just
+/// reuse the first one's answer instead of re-interpreting the callee's MIR.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+struct ConstFnCacheKey<'tcx> {
+    instance: ty::Instance<'tcx>,
+    args: Vec<ConstFnCacheArg>,
+}
+
+/// The cacheable shapes of an evaluated argument. Only small, relocation-free scalars
+/// and scalar pairs are supported: by-ref aggregates aren't cheap to hash or compare,
+/// and a `Scalar::Ptr` embeds an `AllocId` whose identity is local to this one
+/// evaluation, so neither is a sound cache key.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+enum ConstFnCacheArg {
+    Scalar(Scalar),
+    ScalarPair(Scalar, Scalar),
+}
+
+/// Arguments larger than this are not memoized, even if their layout happens to be a
+/// scalar pair, so that a single cache entry can't grow unboundedly large.
+const MAX_CACHEABLE_ARG_SIZE: u64 = 16;
+
+/// How many terminators to evaluate between deadline checks / progress reports. Checking
+/// on every single terminator would make `Instant::now()` a hot-path cost; this amortizes
+/// it while still reporting "still running" often enough to be useful to tooling.
+const PROGRESS_REPORT_INTERVAL: usize = 1_000_000;
+
+/// Whether `before_terminator` should run the deadline/progress check on this step.
+/// Pulled out as a pure function, independent of `steps_remaining`, so that
+/// independence is directly testable: this must stay true on its schedule whether or
+/// not the separate step limit is disabled (`steps_remaining == 0`).
+fn should_check_progress(steps_taken: usize) -> bool {
+    steps_taken % PROGRESS_REPORT_INTERVAL == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn progress_check_runs_on_schedule() {
+        // Regression test for a bug where the deadline/progress check in
+        // `before_terminator` was gated behind `steps_remaining == 0` -- the same
+        // sentinel that means "the step limit is disabled" -- so it never fired at all
+        // for the "unlimited steps, but time-boxed" configuration. `should_check_progress`
+        // takes no `steps_remaining` parameter at all, so there is nothing for such a
+        // sentinel to gate; these are concrete values rather than a restatement of the
+        // function's own modulo so a future edit that breaks the schedule is actually
+        // caught here, not just mirrored.
+        assert!(!should_check_progress(1));
+        assert!(!should_check_progress(PROGRESS_REPORT_INTERVAL - 1));
+        assert!(should_check_progress(PROGRESS_REPORT_INTERVAL));
+        assert!(!should_check_progress(PROGRESS_REPORT_INTERVAL + 1));
+        assert!(should_check_progress(2 * PROGRESS_REPORT_INTERVAL));
+    }
+
+    #[test]
+    fn strict_mode_only_toggles_validity_not_alignment() {
+        // `MemoryExtra::with_strict_mode` only has a real `enforce_validity` bit to
+        // flip: alignment enforcement is still hardcoded in
+        // `Machine::enforce_alignment` (it needs `ConstValue::ByRef` to carry an
+        // `Align`, which is out of reach from this machine). This pins down that
+        // `MemoryExtra` itself makes no claim about alignment it cannot back up.
+        let strict = MemoryExtra::new(false).with_strict_mode();
+        assert!(strict.enforce_validity);
+        assert!(!<CompileTimeInterpreter<'_> as interpret::Machine<'_, '_>>::enforce_alignment(
+            &strict
+        ));
+
+        let lenient = MemoryExtra::new(false);
+        assert!(!lenient.enforce_validity);
+    }
+}
+
+impl<'mir, 'tcx> InterpCx<'mir, 'tcx, CompileTimeInterpreter<'tcx>> {
+    /// Tries to view `arg` as a [`ConstFnCacheArg`], i.e. something small and
+    /// relocation-free enough to memoize a call on. Returns `None` for anything else.
+    fn const_fn_cache_arg(&self, arg: OpTy<'tcx>) -> InterpResult<'tcx, Option<ConstFnCacheArg>> {
+        use rustc_target::abi::Abi;
+
+        if arg.layout.size.bytes() > MAX_CACHEABLE_ARG_SIZE {
+            return Ok(None);
+        }
+
+        Ok(match arg.layout.abi {
+            Abi::Scalar(_) => match self.read_immediate(arg)?.to_scalar() {
+                Ok(scalar @ Scalar::Raw { .. }) => Some(ConstFnCacheArg::Scalar(scalar)),
+                // A real pointer: its `AllocId` is only meaningful in this evaluation.
+                _ => None,
+            },
+            Abi::ScalarPair(..) => {
+                let a = self.read_immediate(self.operand_field(arg, 0)?)?.to_scalar();
+                let b = self.read_immediate(self.operand_field(arg, 1)?)?.to_scalar();
+                match (a, b) {
+                    (Ok(a @ Scalar::Raw { .. }), Ok(b @ Scalar::Raw { .. })) => {
+                        Some(ConstFnCacheArg::ScalarPair(a, b))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        })
+    }
+    /// Evaluate a const function call, memoizing the result where we can: calls whose
+    /// arguments are all zero-sized types go through the query system (and so are
+    /// memoized across the whole compilation), and calls with small scalar arguments
+    /// and a scalar or scalar-pair result are memoized locally for the rest of this
+    /// evaluation session.
     ///
     /// Returns `true` if the call has been evaluated.
     fn try_eval_const_fn_call(
@@ -36,24 +145,83 @@ impl<'mir, 'tcx> InterpCx<'mir, 'tcx, CompileTimeInterpreter> {
         if instance.def.requires_caller_location(self.tcx()) {
             return Ok(false);
         }
-        // For the moment we only do this for functions which take no arguments
-        // (or all arguments are ZSTs) so that we don't memoize too much.
-        if args.iter().any(|a| !a.layout.is_zst()) {
-            return Ok(false);
-        }
-
         let dest = match ret {
             Some((dest, _)) => dest,
             // Don't memoize diverging function calls.
             None => return Ok(false),
         };
 
-        let gid = GlobalId { instance, promoted: None };
+        if args.iter().all(|a| a.layout.is_zst()) {
+            // All arguments are ZSTs, so the result only depends on `instance`: go
+            // through the query system, which memoizes across the whole compilation.
+            let gid = GlobalId { instance, promoted: None };
+            let place = self.const_eval_raw(gid)?;
+            self.copy_op(place.into(), dest)?;
+            self.return_to_block(ret.map(|r| r.1))?;
+            self.dump_place(*dest);
+            return Ok(true);
+        }
 
-        let place = self.const_eval_raw(gid)?;
+        // Opt-in memoization for calls with small, relocation-free scalar arguments.
+        // This is local to this evaluation session (unlike the ZST path above, which
+        // is backed by the query system), but still saves re-interpreting the same
+        // hot `const fn` body over and over with the same arguments.
+        // The cached result is an `Immediate`, so the return value must be one too.
+        {
+            use rustc_target::abi::Abi;
+            if !matches!(dest.layout.abi, Abi::Scalar(_) | Abi::ScalarPair(..)) {
+                return Ok(false);
+            }
+        }
+        let mut key_args = Vec::with_capacity(args.len());
+        for arg in args {
+            match self.const_fn_cache_arg(*arg)? {
+                Some(cache_arg) => key_args.push(cache_arg),
+                // By-ref aggregate or a real pointer: fall back to normal evaluation.
+                None => return Ok(false),
+            }
+        }
+        let key = ConstFnCacheKey { instance, args: key_args };
+
+        if let Some(cached) = self.machine.const_fn_cache.get(&key) {
+            let cached = *cached;
+            self.write_immediate(cached, dest)?;
+            self.return_to_block(ret.map(|r| r.1))?;
+            self.dump_place(*dest);
+            return Ok(true);
+        }
 
-        self.copy_op(place.into(), dest)?;
+        // Cache miss: evaluate the call once, in a fresh nested frame, so we can read
+        // back its result and remember it under `key` for next time.
+        let body = self.load_mir(instance.def, None)?;
+        let frame_idx = self.stack().len();
+        self.push_stack_frame(
+            instance,
+            body.span,
+            body,
+            Some(dest),
+            StackPopCleanup::None { cleanup: false },
+        )?;
+
+        // `push_stack_frame` does not itself populate the callee's argument locals --
+        // that is normally done by the ordinary call-handling code in `terminator.rs`.
+        // Argument locals are numbered `1..=args.len()`, right after the return-place
+        // local `0`, so copy each evaluated argument into the matching local here.
+        for (i, arg) in args.iter().enumerate() {
+            let arg_dest = self.eval_place(&mir::Place::from(mir::Local::new(i + 1)))?;
+            self.copy_op(*arg, arg_dest)?;
+        }
 
+        // `self.run()` steps until the *entire* stack is empty, which would run the rest
+        // of the enclosing evaluation too, since our caller's frames are still on the
+        // stack below the one we just pushed. Step only until our frame (and anything it
+        // calls) has popped back off instead.
+        while self.stack().len() > frame_idx {
+            self.step()?;
+        }
+
+        let result = self.read_immediate(self.place_to_op(dest)?)?;
+        self.machine.const_fn_cache.insert(key, *result);
         self.return_to_block(ret.map(|r| r.1))?;
         self.dump_place(*dest);
         Ok(true)
@@ -86,23 +254,88 @@ impl<'mir, 'tcx> InterpCx<'mir, 'tcx, CompileTimeInterpreter> {
 }
 
 /// Extra machine state for CTFE, and the Machine instance
-pub struct CompileTimeInterpreter {
+pub struct CompileTimeInterpreter<'tcx> {
     /// For now, the number of terminators that can be evaluated before we throw a resource
     /// exhuastion error.
     ///
     /// Setting this to `0` disables the limit and allows the interpreter to run forever.
     pub steps_remaining: usize,
+
+    /// Memoized results of `const fn` calls whose arguments are not all ZSTs, keyed on
+    /// the instance being called together with its argument values. See
+    /// `try_eval_const_fn_call` for how entries are populated and consulted.
+    const_fn_cache: FxHashMap<ConstFnCacheKey<'tcx>, Immediate>,
+
+    /// A wall-clock deadline past which evaluation aborts with
+    /// `ConstEvalErrKind::DeadlineExceeded`, checked every `PROGRESS_REPORT_INTERVAL`
+    /// terminators alongside `steps_remaining`. `None` means no deadline.
+    deadline: Option<Instant>,
+
+    /// Invoked every `PROGRESS_REPORT_INTERVAL` terminators with the number of
+    /// terminators evaluated so far, so tooling can surface a "const evaluation still
+    /// running" diagnostic for slow-but-progressing computations. `None` disables
+    /// progress reporting.
+    progress_callback: Option<Box<dyn FnMut(usize) + Send>>,
+
+    /// Total terminators evaluated so far; reported to `progress_callback`.
+    steps_taken: usize,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct MemoryExtra {
     /// Whether this machine may read from statics
     pub(super) can_access_statics: bool,
+
+    /// Whether to run the validity checker after every assignment into a typed place,
+    /// so that producing an invalid value (an out-of-range `bool`, a dangling reference
+    /// from a `transmute`, ...) is caught eagerly instead of passing through const-eval
+    /// unnoticed. Unlike alignment enforcement (see `Machine::enforce_alignment` below),
+    /// this does not depend on anything outside this machine, so it is safe to make
+    /// configurable here.
+    pub(super) enforce_validity: bool,
+}
+
+impl MemoryExtra {
+    pub(super) fn new(can_access_statics: bool) -> Self {
+        MemoryExtra { can_access_statics, enforce_validity: false }
+    }
+
+    /// Opts this machine into eager validity checking (see `enforce_validity` above), so
+    /// `const` initializers evaluated with it catch invalid-value UB eagerly instead of
+    /// taking the normal, cheaper path. Alignment enforcement cannot be made configurable
+    /// the same way yet; see `Machine::enforce_alignment`.
+    pub(super) fn with_strict_mode(mut self) -> Self {
+        self.enforce_validity = true;
+        self
+    }
 }
 
-impl CompileTimeInterpreter {
+impl<'tcx> CompileTimeInterpreter<'tcx> {
     pub(super) fn new(const_eval_limit: usize) -> Self {
-        CompileTimeInterpreter { steps_remaining: const_eval_limit }
+        CompileTimeInterpreter {
+            steps_remaining: const_eval_limit,
+            const_fn_cache: FxHashMap::default(),
+            deadline: None,
+            progress_callback: None,
+            steps_taken: 0,
+        }
+    }
+
+    /// Sets a wall-clock deadline past which evaluation aborts instead of only bailing
+    /// out on the step limit. Opt-in: callers that don't need this can leave it unset.
+    pub(super) fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Sets a callback invoked periodically with the number of terminators evaluated so
+    /// far, so tooling can surface "const evaluation still running" diagnostics.
+    pub(super) fn with_progress_callback(
+        mut self,
+        progress_callback: Option<Box<dyn FnMut(usize) + Send>>,
+    ) -> Self {
+        self.progress_callback = progress_callback;
+        self
     }
 }
 
@@ -156,7 +389,7 @@ impl<K: Hash + Eq, V> interpret::AllocMap<K, V> for FxHashMap<K, V> {
     }
 }
 
-crate type CompileTimeEvalContext<'mir, 'tcx> = InterpCx<'mir, 'tcx, CompileTimeInterpreter>;
+crate type CompileTimeEvalContext<'mir, 'tcx> = InterpCx<'mir, 'tcx, CompileTimeInterpreter<'tcx>>;
 
 impl interpret::MayLeak for ! {
     #[inline(always)]
@@ -166,7 +399,7 @@ impl interpret::MayLeak for ! {
     }
 }
 
-impl<'mir, 'tcx> interpret::Machine<'mir, 'tcx> for CompileTimeInterpreter {
+impl<'mir, 'tcx> interpret::Machine<'mir, 'tcx> for CompileTimeInterpreter<'tcx> {
     type MemoryKind = !;
     type PointerTag = ();
     type ExtraFnVal = !;
@@ -181,14 +414,22 @@ impl<'mir, 'tcx> interpret::Machine<'mir, 'tcx> for CompileTimeInterpreter {
 
     #[inline(always)]
     fn enforce_alignment(_memory_extra: &Self::MemoryExtra) -> bool {
-        // We do not check for alignment to avoid having to carry an `Align`
-        // in `ConstValue::ByRef`.
+        // We do not check for alignment to avoid having to carry an `Align` in
+        // `ConstValue::ByRef`. Unlike `enforce_validity` below, this cannot be made a
+        // `MemoryExtra` toggle without first giving `ConstValue::ByRef` somewhere to
+        // carry that `Align` -- and `ConstValue` lives in `rustc_middle::mir::interpret`,
+        // outside this machine, so that is a separate, larger change.
         false
     }
 
     #[inline(always)]
-    fn enforce_validity(_ecx: &InterpCx<'mir, 'tcx, Self>) -> bool {
-        false // for now, we don't enforce validity
+    fn enforce_validity(ecx: &InterpCx<'mir, 'tcx, Self>) -> bool {
+        // Unlike `enforce_alignment`, running the validity checker after an assignment
+        // does not need any extra state threaded through `ConstValue`, so this can be a
+        // plain `MemoryExtra` toggle: `with_strict_mode` turns it on so every assignment
+        // into a typed place is checked instead of letting an invalid value (e.g. from a
+        // `transmute`) pass through unnoticed.
+        ecx.memory.extra.enforce_validity
     }
 
     fn find_mir_or_eval_fn(
@@ -294,16 +535,91 @@ impl<'mir, 'tcx> interpret::Machine<'mir, 'tcx> for CompileTimeInterpreter {
     }
 
     fn ptr_to_int(_mem: &Memory<'mir, 'tcx, Self>, _ptr: Pointer) -> InterpResult<'tcx, u64> {
+        // Every `Pointer` that reaches this function via a `ptr as usize` cast is a real
+        // pointer into some tracked allocation in this interpreter -- an integer that was
+        // cast *to* a pointer is represented as `Scalar::Raw`, not as a `Pointer` with no
+        // backing allocation, so there is no "absolute address" variant of `Pointer` for
+        // us to allow through here. A real allocation's address is not known until the
+        // linker lays things out, so we have to keep rejecting this cast unconditionally
+        // until `Pointer`/`Scalar` grow a representation for addresses that are genuinely
+        // known at compile time.
         Err(ConstEvalErrKind::NeedsRfc("pointer-to-integer cast".to_string()).into())
     }
 
     fn binary_ptr_op(
-        _ecx: &InterpCx<'mir, 'tcx, Self>,
-        _bin_op: mir::BinOp,
-        _left: ImmTy<'tcx>,
-        _right: ImmTy<'tcx>,
+        ecx: &InterpCx<'mir, 'tcx, Self>,
+        bin_op: mir::BinOp,
+        left: ImmTy<'tcx>,
+        right: ImmTy<'tcx>,
     ) -> InterpResult<'tcx, (Scalar, bool, Ty<'tcx>)> {
-        Err(ConstEvalErrKind::NeedsRfc("pointer arithmetic or comparison".to_string()).into())
+        use rustc_middle::mir::BinOp::*;
+
+        match bin_op {
+            // Pointer comparisons are sound as long as both pointers point into the *same*
+            // allocation: we can compare their offsets directly. Comparing pointers into two
+            // different allocations would require knowing their relative placement in memory,
+            // which is not decided until the linker runs, so we reject that case instead of
+            // guessing.
+            Eq | Ne | Lt | Le | Gt | Ge => {
+                let left = left.to_scalar()?.to_ptr()?;
+                let right = right.to_scalar()?.to_ptr()?;
+                if left.alloc_id != right.alloc_id {
+                    throw_unsup_format!(
+                        "unable to compare pointers into different allocations at compile-time"
+                    );
+                }
+                let res = match bin_op {
+                    Eq => left.offset == right.offset,
+                    Ne => left.offset != right.offset,
+                    Lt => left.offset < right.offset,
+                    Le => left.offset <= right.offset,
+                    Gt => left.offset > right.offset,
+                    Ge => left.offset >= right.offset,
+                    _ => unreachable!("not a pointer comparison"),
+                };
+                Ok((Scalar::from_bool(res), false, ecx.tcx.types.bool))
+            }
+            // `Offset` arithmetic is sound as long as the result stays in-bounds of the
+            // allocation the pointer started out in.
+            Offset => {
+                let ptr = left.to_scalar()?.to_ptr()?;
+                // `offset`/`arith_offset` take an *element* count, not a byte count: scale
+                // it by the pointee's size the same way codegen's GEP would, instead of
+                // adding it to the byte offset directly.
+                let element_count = right.to_scalar()?.to_machine_isize(ecx)?;
+                let pointee_ty = left
+                    .layout
+                    .ty
+                    .builtin_deref(true)
+                    .expect("`Offset` called on non-pointer type")
+                    .ty;
+                let pointee_size = ecx.layout_of(pointee_ty)?.size.bytes() as i64;
+                // Use checked arithmetic throughout: a huge `element_count` times a large
+                // `pointee_size` can overflow `i64`, and without the checks that would
+                // either panic on overflow (debug builds) or silently wrap into a bogus,
+                // in-bounds-looking offset that slips past the bounds check below
+                // (release builds).
+                let byte_offset = element_count
+                    .checked_mul(pointee_size)
+                    .and_then(|o| o.checked_add(ptr.offset.bytes() as i64));
+                let size = ecx.memory.get(ptr.alloc_id)?.size.bytes() as i64;
+                let new_offset = match byte_offset {
+                    Some(new_offset) if new_offset >= 0 && new_offset <= size => new_offset,
+                    _ => {
+                        throw_unsup_format!(
+                            "`offset` pointer arithmetic out-of-bounds at compile-time"
+                        );
+                    }
+                };
+                let new_ptr = Pointer::new(ptr.alloc_id, Size::from_bytes(new_offset as u64));
+                Ok((Scalar::Ptr(new_ptr), false, left.layout.ty))
+            }
+            _ => Err(ConstEvalErrKind::NeedsRfc(format!(
+                "pointer arithmetic or comparison ({:?})",
+                bin_op
+            ))
+            .into()),
+        }
     }
 
     #[inline(always)]
@@ -328,8 +644,31 @@ impl<'mir, 'tcx> interpret::Machine<'mir, 'tcx> for CompileTimeInterpreter {
     }
 
     fn before_terminator(ecx: &mut InterpCx<'mir, 'tcx, Self>) -> InterpResult<'tcx> {
-        // The step limit has already been hit in a previous call to `before_terminator`.
+        // `steps_remaining == 0` doubles as "the step limit is disabled" (see
+        // `CompileTimeInterpreter::new`) and as "we already hit the limit on a previous
+        // call", so it must not gate the deadline/progress check below: the natural
+        // "unlimited steps, but time-boxed" configuration is `steps_remaining == 0`
+        // together with a deadline, and that combination still needs every call here to
+        // reach the check, not just return early.
+        ecx.machine.steps_taken += 1;
+
+        if should_check_progress(ecx.machine.steps_taken) {
+            // A genuine infinite loop still hits `steps_remaining == 0` below and is
+            // reported as `StepLimitReached`; this is for a computation that is merely
+            // slow but still making progress, which the deadline and progress callback
+            // let tooling distinguish from the former.
+            if let Some(deadline) = ecx.machine.deadline {
+                if Instant::now() >= deadline {
+                    return Err(ConstEvalErrKind::DeadlineExceeded.into());
+                }
+            }
+            if let Some(progress_callback) = &mut ecx.machine.progress_callback {
+                progress_callback(ecx.machine.steps_taken);
+            }
+        }
+
         if ecx.machine.steps_remaining == 0 {
+            // The step limit is either disabled or was already hit on a previous call.
             return Ok(());
         }
 
@@ -151,6 +151,97 @@ fn lower_intrinsic_in_basic_block<'tcx>(tcx: &ty::ctxt<'tcx>,
             )
         });
 
+    } else if name == "min_align_of" || name == "align_of" {
+
+        // dest = min_align_of<T>()
+        //   =>
+        // Assign(dest, AlignOf(T))
+        basic_block.statements.push(Statement {
+            span: span,
+            kind: StatementKind::Assign(
+                call_data.destination.clone(),
+                Rvalue::AlignOf(*substs.types.get(subst::FnSpace, 0))
+            )
+        });
+
+    } else if name == "overflowing_add" || name == "overflowing_sub" || name == "overflowing_mul" {
+
+        assert_eq!(call_data.args.len(), 2);
+
+        let op = match name {
+            "overflowing_add" => BinOp::Add,
+            "overflowing_sub" => BinOp::Sub,
+            "overflowing_mul" => BinOp::Mul,
+            _ => unreachable!()
+        };
+
+        // dest = overflowing_add(a, b)
+        //   =>
+        // Assign(dest, CheckedBinaryOp(Add, a, b))
+        //
+        // `dest` is already typed as the `(T, bool)` tuple that the
+        // intrinsic returns, so `CheckedBinaryOp` can write straight
+        // into it without us building the tuple projections by hand.
+        basic_block.statements.push(Statement {
+            span: span,
+            kind: StatementKind::Assign(
+                call_data.destination.clone(),
+                Rvalue::CheckedBinaryOp(op,
+                                        call_data.args[0].clone(),
+                                        call_data.args[1].clone())
+            )
+        });
+
+    } else if name.starts_with("wrapping_") {
+
+        assert_eq!(call_data.args.len(), 2);
+
+        let op = match &name[9..] {
+            "add" => BinOp::Add,
+            "sub" => BinOp::Sub,
+            "mul" => BinOp::Mul,
+            _ => tcx.sess.span_bug(span, "unknown wrapping intrinsic")
+        };
+
+        // dest = wrapping_add(a, b)
+        //   =>
+        // Assign(dest, BinaryOp(Add, a, b))
+        //
+        // `BinaryOp` on integers already computes the wrapped result;
+        // the overflow check that `CheckedBinaryOp` adds is exactly
+        // what distinguishes the two, so plain `BinaryOp` is enough.
+        basic_block.statements.push(Statement {
+            span: span,
+            kind: StatementKind::Assign(
+                call_data.destination.clone(),
+                Rvalue::BinaryOp(op,
+                                 call_data.args[0].clone(),
+                                 call_data.args[1].clone())
+            )
+        });
+
+    } else if name == "offset" || name == "arith_offset" {
+
+        assert_eq!(call_data.args.len(), 2);
+
+        // dest = offset(ptr, count)
+        //   =>
+        // Assign(dest, BinaryOp(Offset, ptr, count))
+        //
+        // `count` here is still an *element* count, not a byte count: `BinOp::Offset`
+        // carries `ptr`'s pointee type along with it, so each consumer of this `Rvalue`
+        // (codegen's GEP, const-eval's `binary_ptr_op`) is responsible for scaling
+        // `count` by the size of `*substs.types.get(subst::FnSpace, 0)` itself.
+        basic_block.statements.push(Statement {
+            span: span,
+            kind: StatementKind::Assign(
+                call_data.destination.clone(),
+                Rvalue::BinaryOp(BinOp::Offset,
+                                 call_data.args[0].clone(),
+                                 call_data.args[1].clone())
+            )
+        });
+
     } else if name == "type_name" {
 
         let tp_ty = *substs.types.get(subst::FnSpace, 0);
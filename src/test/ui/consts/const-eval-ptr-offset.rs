@@ -0,0 +1,27 @@
+// run-pass
+// Regression test: `arith_offset`/`offset` take an *element* count, so a `u64` pointer
+// advanced by 1 must move 8 bytes, not 1. Also exercises same-allocation pointer
+// comparisons, which are allowed in a const context while cross-allocation comparisons
+// are not.
+
+const OFFSET_SCALED_CORRECTLY: bool = {
+    let xs: [u64; 4] = [1, 2, 3, 4];
+    let p = xs.as_ptr();
+    unsafe {
+        // If `offset` were not scaled by `size_of::<u64>()`, this would land inside the
+        // first element's bytes rather than on the second element.
+        *p.offset(1) == 2
+    }
+};
+
+const SAME_ALLOC_COMPARISON: bool = {
+    let xs: [u8; 4] = [0, 0, 0, 0];
+    let p = xs.as_ptr();
+    let q = unsafe { p.offset(2) };
+    q != p && q > p && unsafe { q.offset_from(p) == 2 }
+};
+
+fn main() {
+    assert!(OFFSET_SCALED_CORRECTLY);
+    assert!(SAME_ALLOC_COMPARISON);
+}
@@ -0,0 +1,20 @@
+// run-pass
+// Regression test: memoizing `const fn` calls keyed on their scalar arguments must
+// actually thread those arguments into the memoized evaluation. A naive cache that
+// evaluates the callee with uninitialized locals would get every call after the first
+// wrong (or error out), since the arguments never reach the function body.
+
+const fn add(a: u32, b: u32) -> u32 {
+    a + b
+}
+
+const A: u32 = add(1, 2);
+const B: u32 = add(10, 20);
+const C: u32 = add(1, 2);
+
+fn main() {
+    assert_eq!(A, 3);
+    assert_eq!(B, 30);
+    assert_eq!(C, 3);
+    assert_eq!(add(100, 200), 300);
+}
@@ -0,0 +1,27 @@
+// run-pass
+// Regression test for the `LowerIntrinsics` MIR pass: `align_of`, the `overflowing_*`
+// and `wrapping_*` families, and `offset`/`arith_offset` should all keep their normal
+// runtime semantics once they are lowered to plain `Rvalue`s instead of being left as
+// intrinsic calls for codegen to handle.
+
+use std::mem;
+
+fn main() {
+    assert_eq!(mem::align_of::<u32>(), mem::min_align_of::<u32>());
+
+    assert_eq!(1u8.overflowing_add(2), (3, false));
+    assert_eq!(255u8.overflowing_add(1), (0, true));
+    assert_eq!(0u8.overflowing_sub(1), (255, true));
+    assert_eq!(200u8.overflowing_mul(2), (144, true));
+
+    assert_eq!(255u8.wrapping_add(1), 0);
+    assert_eq!(0u8.wrapping_sub(1), 255);
+    assert_eq!(200u8.wrapping_mul(2), 144);
+
+    let xs = [10i32, 20, 30, 40];
+    let p = xs.as_ptr();
+    unsafe {
+        assert_eq!(*p.offset(2), 30);
+        assert_eq!(*p.wrapping_offset(1).offset(1), 30);
+    }
+}